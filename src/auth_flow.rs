@@ -13,31 +13,57 @@ use crate::{
     QuickPayClient,
 };
 
+/// Which kind of resource an authorization flow is being driven for. Payments
+/// and mandates expose identical authorization-flow endpoints, just under
+/// different API paths, so the flow itself can be shared.
+enum AuthFlowResource<'a> {
+    Payment(&'a str),
+    Mandate(&'a str),
+}
+
 impl QuickPayClient {
     pub async fn handle_auth_flow(&self, payment_id: &str) -> Result<(), anyhow::Error> {
-        let mut auth_flow = self
-            .tl
-            .payments
-            .start_authorization_flow(
-                payment_id,
-                &StartAuthorizationFlowRequest {
-                    provider_selection: Some(ProviderSelectionSupported {}),
-                    redirect: Some(RedirectSupported {
-                        return_uri: self.redirect_uri.clone(),
-                        direct_return_uri: None,
-                    }),
-                    consent: Some(ConsentSupported {}),
-                    form: Some(FormSupported {
-                        input_types: vec![
-                            AdditionalInputType::Text,
-                            AdditionalInputType::TextWithImage,
-                            AdditionalInputType::Select,
-                        ],
-                    }),
-                },
-            )
-            .await?
-            .authorization_flow;
+        self.run_auth_flow(AuthFlowResource::Payment(payment_id))
+            .await
+    }
+
+    pub async fn handle_mandate_auth_flow(&self, mandate_id: &str) -> Result<(), anyhow::Error> {
+        self.run_auth_flow(AuthFlowResource::Mandate(mandate_id))
+            .await
+    }
+
+    async fn run_auth_flow(&self, resource: AuthFlowResource<'_>) -> Result<(), anyhow::Error> {
+        let request = StartAuthorizationFlowRequest {
+            provider_selection: Some(ProviderSelectionSupported {}),
+            redirect: Some(RedirectSupported {
+                return_uri: self.redirect_uri.clone(),
+                direct_return_uri: None,
+            }),
+            consent: Some(ConsentSupported {}),
+            form: Some(FormSupported {
+                input_types: vec![
+                    AdditionalInputType::Text,
+                    AdditionalInputType::TextWithImage,
+                    AdditionalInputType::Select,
+                ],
+            }),
+        };
+        let mut auth_flow = match resource {
+            AuthFlowResource::Payment(id) => {
+                self.tl
+                    .payments
+                    .start_authorization_flow(id, &request)
+                    .await?
+                    .authorization_flow
+            }
+            AuthFlowResource::Mandate(id) => {
+                self.tl
+                    .mandates
+                    .start_authorization_flow(id, &request)
+                    .await?
+                    .authorization_flow
+            }
+        };
 
         while let Some(auth_flow_inner) = auth_flow {
             auth_flow = match auth_flow_inner
@@ -46,17 +72,17 @@ impl QuickPayClient {
                 .next
             {
                 AuthorizationFlowNextAction::ProviderSelection { providers } => {
-                    self.handle_provider_selection(payment_id, &providers)
+                    self.handle_provider_selection(&resource, &providers)
                         .await?
                 }
                 AuthorizationFlowNextAction::Consent { .. } => {
-                    self.handle_consent_action(payment_id).await?
+                    self.handle_consent_action(&resource).await?
                 }
                 AuthorizationFlowNextAction::Redirect { uri, .. } => {
                     self.handle_redirect_action(&uri)
                 }
                 AuthorizationFlowNextAction::Form { inputs, .. } => {
-                    self.handle_form_action(payment_id, &inputs).await?
+                    self.handle_form_action(&resource, &inputs).await?
                 }
                 AuthorizationFlowNextAction::Wait => None,
             }
@@ -66,7 +92,7 @@ impl QuickPayClient {
 
     async fn handle_provider_selection(
         &self,
-        payment_id: &str,
+        resource: &AuthFlowResource<'_>,
         providers: &[Provider],
     ) -> Result<Option<AuthorizationFlow>, anyhow::Error> {
         let provider_names = providers
@@ -84,35 +110,45 @@ impl QuickPayClient {
             .with_prompt("Select provider")
             .interact()?;
         let selected_provider = &providers.get(index).expect("index out of bounds").id;
-        let response = self
-            .tl
-            .payments
-            .submit_provider_selection(
-                payment_id,
-                &SubmitProviderSelectionActionRequest {
-                    provider_id: selected_provider.clone(),
-                },
-            )
-            .await?;
+        let request = SubmitProviderSelectionActionRequest {
+            provider_id: selected_provider.clone(),
+        };
+        let response = match resource {
+            AuthFlowResource::Payment(id) => {
+                self.tl
+                    .payments
+                    .submit_provider_selection(id, &request)
+                    .await?
+            }
+            AuthFlowResource::Mandate(id) => {
+                self.tl
+                    .mandates
+                    .submit_provider_selection(id, &request)
+                    .await?
+            }
+        };
         Ok(response.authorization_flow)
     }
 
     async fn handle_consent_action(
         &self,
-        payment_id: &str,
+        resource: &AuthFlowResource<'_>,
     ) -> Result<Option<AuthorizationFlow>, anyhow::Error> {
         let consent = dialoguer::Confirm::with_theme(&ColorfulTheme::default())
             .with_prompt("Submit consent")
             .wait_for_newline(false)
             .interact()?;
         anyhow::ensure!(consent, "consent was not given");
-        let response = self.tl.payments.submit_consent(payment_id).await?;
+        let response = match resource {
+            AuthFlowResource::Payment(id) => self.tl.payments.submit_consent(id).await?,
+            AuthFlowResource::Mandate(id) => self.tl.mandates.submit_consent(id).await?,
+        };
         Ok(response.authorization_flow)
     }
 
     async fn handle_form_action(
         &self,
-        payment_id: &str,
+        resource: &AuthFlowResource<'_>,
         inputs: &[AdditionalInput],
     ) -> Result<Option<AuthorizationFlow>, anyhow::Error> {
         let mut submissions: HashMap<String, String> = HashMap::with_capacity(inputs.len());
@@ -147,21 +183,28 @@ impl QuickPayClient {
                     ..
                 } => {
                     let img = match image {
-                        AdditionalInputImage::Uri { .. } => {
-                            todo!("URL images are not yet supported")
-                        }
+                        AdditionalInputImage::Uri { uri, .. } => match fetch_image(uri).await {
+                            Ok(img) => Some(img),
+                            Err(err) => {
+                                eprintln!("Could not load challenge image ({err})");
+                                println!("Challenge image: \n{uri}\n");
+                                None
+                            }
+                        },
                         AdditionalInputImage::Base64 { data, .. } => {
                             let img_bytes = base64::decode(data)?;
-                            image::load_from_memory(&img_bytes)?
+                            Some(image::load_from_memory(&img_bytes)?)
                         }
                     };
-                    let conf = viuer::Config {
-                        absolute_offset: false,
-                        width: Some(64),
-                        height: Some(20),
-                        ..Default::default()
-                    };
-                    viuer::print(&img, &conf).expect("Image printing failed");
+                    if let Some(img) = img {
+                        let conf = viuer::Config {
+                            absolute_offset: false,
+                            width: Some(64),
+                            height: Some(20),
+                            ..Default::default()
+                        };
+                        viuer::print(&img, &conf).expect("Image printing failed");
+                    }
                     submissions.insert(
                         id.to_string(),
                         text_input(
@@ -184,16 +227,17 @@ impl QuickPayClient {
                 ),
             };
         }
-        let response = self
-            .tl
-            .payments
-            .submit_form_inputs(
-                payment_id,
-                &SubmitFormActionRequest {
-                    inputs: submissions,
-                },
-            )
-            .await?;
+        let request = SubmitFormActionRequest {
+            inputs: submissions,
+        };
+        let response = match resource {
+            AuthFlowResource::Payment(id) => {
+                self.tl.payments.submit_form_inputs(id, &request).await?
+            }
+            AuthFlowResource::Mandate(id) => {
+                self.tl.mandates.submit_form_inputs(id, &request).await?
+            }
+        };
         Ok(response.authorization_flow)
     }
 
@@ -203,6 +247,24 @@ impl QuickPayClient {
     }
 }
 
+/// Fetches and decodes a challenge image hosted at `uri` (e.g. a QR code or
+/// photo-TAN image some providers return instead of inline base64 data).
+async fn fetch_image(uri: &str) -> Result<image::DynamicImage, anyhow::Error> {
+    let response = reqwest::get(uri).await?.error_for_status()?;
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    anyhow::ensure!(
+        content_type.starts_with("image/"),
+        "expected an image, got content type \"{content_type}\""
+    );
+    let bytes = response.bytes().await?;
+    Ok(image::load_from_memory(&bytes)?)
+}
+
 fn to_emoji(country: &CountryCode) -> &str {
     match country {
         CountryCode::DE => "🇩🇪",
@@ -1,4 +1,7 @@
-use serde::Deserialize;
+use std::{fs, path::PathBuf};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize, Clone)]
 pub struct Configuration {
@@ -7,4 +10,66 @@ pub struct Configuration {
     pub client_kid: String,
     pub client_private_key: String,
     pub redirect_uri: String,
+    /// Which TrueLayer environment to target. Defaults to `sandbox`.
+    #[serde(default)]
+    pub environment: Environment,
+    /// Real customer details to attach to created payments/mandates.
+    /// Defaults to the sandbox test user when absent.
+    pub user: Option<User>,
+    /// Restricts which providers are offered during provider selection.
+    pub provider_filter: Option<ProviderFilter>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Environment {
+    #[default]
+    Sandbox,
+    Live,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct User {
+    pub name: String,
+    pub email: String,
+    pub phone: Option<String>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ProviderFilter {
+    /// ISO 3166-1 alpha-2 country codes, e.g. "GB", "DE".
+    pub countries: Option<Vec<String>>,
+    pub release_channel: Option<String>,
+    pub excluded_provider_ids: Option<Vec<String>>,
+}
+
+/// A mandate id persisted alongside the main configuration so `mandate pay`
+/// can reference a previously authorized mandate without repeating the
+/// interactive consent flow.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MandateStore {
+    pub mandate_id: String,
+}
+
+impl MandateStore {
+    pub fn save(mandate_id: &str) -> Result<(), anyhow::Error> {
+        let store = Self {
+            mandate_id: mandate_id.to_string(),
+        };
+        fs::write(Self::path()?, serde_json::to_string_pretty(&store)?)
+            .context("could not persist mandate id")
+    }
+
+    pub fn load() -> Result<Self, anyhow::Error> {
+        let contents = fs::read_to_string(Self::path()?)
+            .context("no mandate found; run `quickpay mandate create` first")?;
+        serde_json::from_str(&contents).context("could not parse stored mandate")
+    }
+
+    fn path() -> Result<PathBuf, anyhow::Error> {
+        Ok(dirs::home_dir()
+            .ok_or_else(|| anyhow!("could not find home dir"))?
+            .join(".config")
+            .join("quickpay-mandate"))
+    }
 }
@@ -1,21 +1,47 @@
 use anyhow::bail;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use truelayer_rust::{
-    apis::payments::{
-        Beneficiary, CreatePaymentRequest, CreatePaymentUserRequest, Currency,
-        PaymentMethodRequest, PaymentStatus, ProviderSelectionRequest, SchemeSelection,
+    apis::{
+        mandates::CreateMandateRequest,
+        payments::{
+            Beneficiary, CreatePaymentRequest, CreatePaymentResponse, CreatePaymentUserRequest,
+            Currency, PaymentMethodRequest, PaymentStatus, ProviderFilter,
+            ProviderSelectionRequest, SchemeSelection,
+        },
     },
     pollable::PollOptions,
     PollableUntilTerminalState, TrueLayerClient,
 };
+use uuid::Uuid;
 
 mod auth_flow;
 pub mod config;
 mod inputs;
+pub mod retry;
+
+pub use retry::Retry;
+
+use crate::config::MandateStore;
 
 pub struct QuickPayClient {
     pub tl: TrueLayerClient,
     pub redirect_uri: String,
+    /// Attached to created payments/mandates; defaults to a sandbox test
+    /// user when the configuration doesn't supply real customer details.
+    pub user: CreatePaymentUserRequest,
+    /// Restricts which providers are offered during provider selection.
+    pub provider_filter: Option<ProviderFilter>,
+}
+
+/// How a payment should be funded.
+pub enum PaymentMethod {
+    /// A one-off bank transfer requiring interactive provider selection and
+    /// consent.
+    BankTransfer { beneficiary: Beneficiary },
+    /// A charge against a previously authorized mandate - no interactive
+    /// steps are needed since consent was already given when the mandate
+    /// was created.
+    Mandate { mandate_id: String },
 }
 
 impl QuickPayClient {
@@ -23,33 +49,121 @@ impl QuickPayClient {
         &self,
         amount: u64,
         currency: Currency,
-        beneficiary: Beneficiary,
+        payment_method: PaymentMethod,
+        retry: Retry,
+        idempotency_key: Option<String>,
     ) -> Result<(), anyhow::Error> {
+        // Generated once, before the first attempt, so that every retry of
+        // this logical operation resubmits under the same key rather than
+        // risking a duplicate payment.
+        let idempotency_key = idempotency_key.unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        let needs_auth_flow = matches!(payment_method, PaymentMethod::BankTransfer { .. });
+        let payment_method = match payment_method {
+            PaymentMethod::BankTransfer { beneficiary } => PaymentMethodRequest::BankTransfer {
+                provider_selection: ProviderSelectionRequest::UserSelected {
+                    filter: self.provider_filter.clone(),
+                    scheme_selection: Some(SchemeSelection::InstantPreferred {
+                        allow_remitter_fee: Some(false),
+                    }),
+                },
+                beneficiary,
+            },
+            PaymentMethod::Mandate { mandate_id } => PaymentMethodRequest::Mandate { mandate_id },
+        };
+
         let payment = self
             .tl
             .payments
-            .create(&CreatePaymentRequest {
-                amount_in_minor: amount,
-                currency,
-                payment_method: PaymentMethodRequest::BankTransfer {
+            .create(
+                &CreatePaymentRequest {
+                    amount_in_minor: amount,
+                    currency,
+                    payment_method,
+                    user: self.user.clone(),
+                    metadata: None,
+                },
+                Some(&idempotency_key),
+            )
+            .await?;
+
+        let mut auth_flow_done = !needs_auth_flow;
+        let first_attempted_at = Instant::now();
+        let mut attempts = 0usize;
+        loop {
+            attempts += 1;
+            match self.attempt_payment(&payment, &mut auth_flow_done).await {
+                Ok(Outcome::Terminal) => return Ok(()),
+                Ok(Outcome::Failed(message)) => bail!(message),
+                Err(err)
+                    if is_retryable(&err) && retry.should_retry(attempts, first_attempted_at) =>
+                {
+                    eprintln!("Attempt {attempts} failed ({err}), retrying...");
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Authorizes a long-lived mandate (Variable Recurring Payment consent)
+    /// and persists its id so future payments can reference it with
+    /// [`PaymentMethod::Mandate`] instead of repeating the interactive flow.
+    pub async fn create_mandate(
+        &self,
+        currency: Currency,
+        beneficiary: Beneficiary,
+        idempotency_key: Option<String>,
+    ) -> Result<String, anyhow::Error> {
+        let idempotency_key = idempotency_key.unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        let mandate = self
+            .tl
+            .mandates
+            .create(
+                &CreateMandateRequest {
+                    currency,
+                    beneficiary,
                     provider_selection: ProviderSelectionRequest::UserSelected {
-                        filter: None,
-                        scheme_selection: Some(SchemeSelection::InstantPreferred {
-                            allow_remitter_fee: Some(false),
-                        }),
+                        filter: self.provider_filter.clone(),
+                        scheme_selection: None,
                     },
-                    beneficiary,
+                    user: self.user.clone(),
                 },
-                user: CreatePaymentUserRequest::NewUser {
-                    name: Some("Name".into()),
-                    email: Some("a@b.com".into()),
-                    phone: None,
-                },
-                metadata: None,
-            })
+                Some(&idempotency_key),
+            )
             .await?;
 
-        self.handle_auth_flow(&payment.id).await?;
+        self.handle_mandate_auth_flow(&mandate.id).await?;
+        MandateStore::save(&mandate.id)?;
+
+        Ok(mandate.id)
+    }
+
+    /// Runs the authorization flow (when required and not already done) and
+    /// polls for a terminal status once.
+    ///
+    /// `auth_flow_done` is only flipped to `true` once `handle_auth_flow`
+    /// actually succeeds, so a retry after a poll failure re-polls the
+    /// already-authorized payment instead of re-driving provider selection
+    /// and consent from scratch.
+    ///
+    /// Failures are returned as `Err`; the caller retries only those
+    /// that [`is_retryable`] accepts (connection failures, timeouts and 5xx
+    /// responses). A status that is still not terminal once polling ends,
+    /// or declined consent, is an `Err` that is not retried. A terminal
+    /// `Failed` payment status is not an error in this sense - retrying a
+    /// payment the provider has already declined wouldn't help - so it's
+    /// surfaced as `Ok(Outcome::Failed(..))` instead and left to the caller
+    /// to turn into the final error.
+    async fn attempt_payment(
+        &self,
+        payment: &CreatePaymentResponse,
+        auth_flow_done: &mut bool,
+    ) -> Result<Outcome, anyhow::Error> {
+        if !*auth_flow_done {
+            self.handle_auth_flow(&payment.id).await?;
+            *auth_flow_done = true;
+        }
 
         let pb = indicatif::ProgressBar::new_spinner()
             .with_message("Polling for terminal payment status");
@@ -57,15 +171,39 @@ impl QuickPayClient {
         let output = payment
             .poll_until_terminal_state(&self.tl, PollOptions::default())
             .await?;
-        pb.finish_with_message(match output.status {
+        Ok(match output.status {
             PaymentStatus::Executed { executed_at, .. } => {
-                format!("Payment executed at {executed_at}")
+                pb.finish_with_message(format!("Payment executed at {executed_at}"));
+                Outcome::Terminal
+            }
+            PaymentStatus::Settled { settled_at, .. } => {
+                pb.finish_with_message(format!("Payment settled at {settled_at}"));
+                Outcome::Terminal
+            }
+            PaymentStatus::Failed { failed_at, .. } => {
+                let message = format!("Payment failed at {failed_at}");
+                pb.finish_with_message(message.clone());
+                Outcome::Failed(message)
             }
-            PaymentStatus::Settled { settled_at, .. } => format!("Payment settled at {settled_at}"),
-            PaymentStatus::Failed { failed_at, .. } => format!("Payment failed at {failed_at}"),
             _ => bail!("Payment did not reach terminal status"),
-        });
-
-        Ok(())
+        })
     }
 }
+
+enum Outcome {
+    Terminal,
+    Failed(String),
+}
+
+/// Whether an error is safe to retry: transient network failures and server
+/// errors are, everything else (bad requests, declined consent, auth
+/// failures) is deterministic and retrying it would just fail again.
+fn is_retryable(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        cause.downcast_ref::<reqwest::Error>().is_some_and(|err| {
+            err.is_connect()
+                || err.is_timeout()
+                || err.status().is_some_and(|s| s.is_server_error())
+        })
+    })
+}
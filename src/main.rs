@@ -1,10 +1,19 @@
 use anyhow::anyhow;
-use clap::{Parser, ValueEnum};
-use truelayer_quickpay::{config::Configuration, QuickPayClient};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::time::Duration;
+use truelayer_quickpay::{
+    config::{
+        Configuration, Environment as ConfigEnvironment, MandateStore,
+        ProviderFilter as ProviderFilterConfig,
+    },
+    PaymentMethod, QuickPayClient, Retry,
+};
 use truelayer_rust::{
     apis::{
         auth::Credentials,
-        payments::{AccountIdentifier, Beneficiary},
+        payments::{
+            AccountIdentifier, Beneficiary, CountryCode, CreatePaymentUserRequest, ProviderFilter,
+        },
     },
     client::Environment,
     TrueLayerClient,
@@ -13,6 +22,21 @@ use truelayer_rust::{
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Make a one-off payment to a beneficiary
+    Pay(PayArgs),
+    /// Manage Variable Recurring Payment mandates
+    #[command(subcommand)]
+    Mandate(MandateCommand),
+}
+
+#[derive(Parser, Debug)]
+struct PayArgs {
     /// Payment currency
     #[arg(value_enum)]
     currency_code: Currency,
@@ -35,6 +59,80 @@ struct Args {
     /// Payment reference
     #[arg(short, long)]
     reference: Option<String>,
+
+    /// Maximum number of attempts before giving up; a retry repeats the
+    /// authorization flow only if it has not yet succeeded, then the status poll
+    #[arg(long, conflicts_with = "retry_timeout")]
+    retry_attempts: Option<usize>,
+
+    /// Maximum time in seconds to keep retrying before giving up
+    #[arg(long, conflicts_with = "retry_attempts")]
+    retry_timeout: Option<u64>,
+
+    /// Idempotency key to use for the payment creation call, so a re-run of
+    /// this exact invocation is safe to retry without double-charging.
+    /// Defaults to a freshly generated UUID.
+    #[arg(long)]
+    idempotency_key: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum MandateCommand {
+    /// Authorize a long-lived mandate and persist its id for `mandate pay`
+    Create {
+        /// Mandate currency
+        #[arg(value_enum)]
+        currency_code: Currency,
+
+        /// Sort code and account number, e.g. "010102,12345678"
+        #[arg(short, long)]
+        scan: Option<Vec<String>>,
+
+        /// IBAN
+        #[arg(short, long)]
+        iban: Option<String>,
+
+        /// Name of the beneficiary
+        #[arg(short, long)]
+        name: String,
+
+        /// Mandate reference
+        #[arg(short, long)]
+        reference: Option<String>,
+
+        /// Idempotency key to use for the mandate creation call. Defaults
+        /// to a freshly generated UUID.
+        #[arg(long)]
+        idempotency_key: Option<String>,
+    },
+    /// Create a payment against a previously authorized mandate, skipping
+    /// provider selection and consent
+    Pay {
+        /// Payment currency
+        #[arg(value_enum)]
+        currency_code: Currency,
+
+        /// Payment amount in currency minor
+        amount: u64,
+
+        /// Id of the mandate to charge; defaults to the one stored by
+        /// `mandate create`
+        #[arg(long)]
+        mandate_id: Option<String>,
+
+        /// Maximum number of attempts before giving up
+        #[arg(long, conflicts_with = "retry_timeout")]
+        retry_attempts: Option<usize>,
+
+        /// Maximum time in seconds to keep retrying before giving up
+        #[arg(long, conflicts_with = "retry_attempts")]
+        retry_timeout: Option<u64>,
+
+        /// Idempotency key to use for the payment creation call. Defaults
+        /// to a freshly generated UUID.
+        #[arg(long)]
+        idempotency_key: Option<String>,
+    },
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -72,6 +170,51 @@ fn account_identifier(
     Err(anyhow::anyhow!("mising account identifier"))
 }
 
+fn retry_policy(attempts: Option<usize>, timeout: Option<u64>) -> Retry {
+    match (attempts, timeout) {
+        (Some(attempts), _) => Retry::Attempts(attempts),
+        (_, Some(secs)) => Retry::Timeout(Duration::from_secs(secs)),
+        (None, None) => Retry::Attempts(1),
+    }
+}
+
+fn country_code(code: &str) -> Result<CountryCode, anyhow::Error> {
+    Ok(match code.to_uppercase().as_str() {
+        "DE" => CountryCode::DE,
+        "ES" => CountryCode::ES,
+        "FR" => CountryCode::FR,
+        "GB" => CountryCode::GB,
+        "IE" => CountryCode::IE,
+        "IT" => CountryCode::IT,
+        "LT" => CountryCode::LT,
+        "NL" => CountryCode::NL,
+        "PL" => CountryCode::PL,
+        "PT" => CountryCode::PT,
+        other => anyhow::bail!("unsupported country code: {other}"),
+    })
+}
+
+fn provider_filter(
+    config: Option<ProviderFilterConfig>,
+) -> Result<Option<ProviderFilter>, anyhow::Error> {
+    let Some(config) = config else {
+        return Ok(None);
+    };
+    Ok(Some(ProviderFilter {
+        countries: config
+            .countries
+            .map(|codes| {
+                codes
+                    .iter()
+                    .map(|c| country_code(c))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .transpose()?,
+        release_channel: config.release_channel,
+        excludes: config.excluded_provider_ids,
+    }))
+}
+
 pub fn get_configuration() -> Result<Configuration, anyhow::Error> {
     let config = config::Config::builder()
         .add_source(
@@ -89,34 +232,110 @@ pub fn get_configuration() -> Result<Configuration, anyhow::Error> {
     Ok(config)
 }
 
-#[tokio::main]
-async fn main() -> Result<(), anyhow::Error> {
-    let args = Args::parse();
-
-    let beneficiary = Beneficiary::ExternalAccount {
-        account_holder_name: args.name,
-        reference: args.reference.unwrap_or_else(|| "reference".into()),
-        account_identifier: account_identifier(args.scan, args.iban)?,
+fn build_client(configuration: Configuration) -> Result<QuickPayClient, anyhow::Error> {
+    let environment = match configuration.environment {
+        ConfigEnvironment::Sandbox => Environment::Sandbox,
+        ConfigEnvironment::Live => Environment::Live,
     };
+    let user = match configuration.user {
+        Some(user) => CreatePaymentUserRequest::NewUser {
+            name: Some(user.name),
+            email: Some(user.email),
+            phone: user.phone,
+        },
+        None => CreatePaymentUserRequest::NewUser {
+            name: Some("Name".into()),
+            email: Some("a@b.com".into()),
+            phone: None,
+        },
+    };
+    let provider_filter = provider_filter(configuration.provider_filter)?;
 
-    let configuration = get_configuration()?;
-
-    let client = QuickPayClient {
+    Ok(QuickPayClient {
         tl: TrueLayerClient::builder(Credentials::ClientCredentials {
             client_id: configuration.client_id,
             client_secret: configuration.client_secret.into(),
             scope: "payments".into(),
         })
-        .with_environment(Environment::Sandbox)
+        .with_environment(environment)
         .with_signing_key(
             &configuration.client_kid,
             configuration.client_private_key.into_bytes(),
         )
         .build(),
         redirect_uri: configuration.redirect_uri,
-    };
+        user,
+        provider_filter,
+    })
+}
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    let args = Args::parse();
+    let configuration = get_configuration()?;
+    let client = build_client(configuration)?;
 
-    client
-        .create(args.amount, args.currency_code.into(), beneficiary)
-        .await
+    match args.command {
+        Command::Pay(pay_args) => {
+            let beneficiary = Beneficiary::ExternalAccount {
+                account_holder_name: pay_args.name,
+                reference: pay_args.reference.unwrap_or_else(|| "reference".into()),
+                account_identifier: account_identifier(pay_args.scan, pay_args.iban)?,
+            };
+            let retry = retry_policy(pay_args.retry_attempts, pay_args.retry_timeout);
+
+            client
+                .create(
+                    pay_args.amount,
+                    pay_args.currency_code.into(),
+                    PaymentMethod::BankTransfer { beneficiary },
+                    retry,
+                    pay_args.idempotency_key,
+                )
+                .await
+        }
+        Command::Mandate(MandateCommand::Create {
+            currency_code,
+            scan,
+            iban,
+            name,
+            reference,
+            idempotency_key,
+        }) => {
+            let beneficiary = Beneficiary::ExternalAccount {
+                account_holder_name: name,
+                reference: reference.unwrap_or_else(|| "reference".into()),
+                account_identifier: account_identifier(scan, iban)?,
+            };
+            let mandate_id = client
+                .create_mandate(currency_code.into(), beneficiary, idempotency_key)
+                .await?;
+            println!("Mandate authorized and stored: {mandate_id}");
+            Ok(())
+        }
+        Command::Mandate(MandateCommand::Pay {
+            currency_code,
+            amount,
+            mandate_id,
+            retry_attempts,
+            retry_timeout,
+            idempotency_key,
+        }) => {
+            let mandate_id = match mandate_id {
+                Some(mandate_id) => mandate_id,
+                None => MandateStore::load()?.mandate_id,
+            };
+            let retry = retry_policy(retry_attempts, retry_timeout);
+
+            client
+                .create(
+                    amount,
+                    currency_code.into(),
+                    PaymentMethod::Mandate { mandate_id },
+                    retry,
+                    idempotency_key,
+                )
+                .await
+        }
+    }
 }
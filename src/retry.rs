@@ -0,0 +1,30 @@
+use std::time::{Duration, Instant};
+
+/// Retry policy for the authorization flow and terminal-status polling in
+/// [`crate::QuickPayClient::create`].
+///
+/// Modelled after rust-lightning's `Retry`/`PaymentAttempts`: a payment is
+/// retried either a fixed number of times, or until a timeout elapses,
+/// whichever the caller prefers. Elapsed time is always measured against a
+/// monotonic clock so the policy is immune to wall-clock adjustments.
+#[derive(Debug, Clone, Copy)]
+pub enum Retry {
+    /// Make at most this many attempts in total, the first one included.
+    Attempts(usize),
+    /// Keep retrying as long as we're still within this duration of the
+    /// first attempt.
+    Timeout(Duration),
+}
+
+impl Retry {
+    /// Whether another attempt should be made, given how many attempts have
+    /// been made so far and when the first one started.
+    pub fn should_retry(&self, attempts: usize, first_attempted_at: Instant) -> bool {
+        match self {
+            Retry::Attempts(max_attempts) => attempts < *max_attempts,
+            Retry::Timeout(max_duration) => {
+                Instant::now().duration_since(first_attempted_at) <= *max_duration
+            }
+        }
+    }
+}